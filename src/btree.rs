@@ -11,6 +11,8 @@ will stipulate that a `Node` fits on a single page.)
 extern crate suffix;
 
 use std::borrow::{Cow, IntoCow};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::iter::{self, repeat};
 use suffix::SuffixTable;
@@ -24,13 +26,17 @@ macro_rules! lg {
 
 /// A suffix database represented with a btree.
 struct SufDB {
-    /// All nodes in the tree.
-    nodes: Vec<Node>,
+    /// Page-backed node store with a bounded LRU buffer cache. Every access
+    /// to a `Node` goes through the pager so the tree behaves like an
+    /// external-memory structure rather than keeping every node resident.
+    pager: Pager,
     /// Pointer to root node.
     root: NodeId,
     /// All documents. The values of a tree are pulled from a document.
     /// (But note that a value is a single suffix!)
     documents: Vec<Document>,
+    /// Document slots freed by `remove_document`, available for reuse.
+    free_docs: Vec<DocId>,
     /// The Knuth order of the tree (maximum number of children in an
     /// internal node).
     order: usize,
@@ -90,31 +96,50 @@ impl SufDB {
     }
 
     fn with_order(order: usize) -> SufDB {
+        SufDB::with_order_and_cache(order, DEFAULT_CACHE_CAP)
+    }
+
+    /// Like `with_order`, but with an explicit buffer-cache capacity (number
+    /// of nodes that may stay resident at once).
+    fn with_order_and_cache(order: usize, cache_cap: usize) -> SufDB {
         assert!(order > 2);
+        let mut pager = Pager::new(cache_cap);
+        let root = pager.alloc(Node::empty());
         SufDB {
-            nodes: vec![Node::empty()],
-            root: 0,
+            pager: pager,
+            root: root,
             documents: vec![],
+            free_docs: vec![],
             order: order,
         }
     }
 
     fn max_children(&self) -> usize { self.order }
     fn max_keys(&self) -> usize { self.order - 1 }
+    // A non-root node keeps at least `ceil(order/2)` children, i.e. this many
+    // keys, so merging a node one key short of this with a minimal sibling
+    // (plus the pulled-down separator) never exceeds `max_keys`.
+    fn min_keys(&self) -> usize { (self.order + 1) / 2 - 1 }
 
     fn is_root(&self, nid: NodeId) -> bool { self.root == nid }
 
-    fn root(&mut self) -> &mut Node {
-        &mut self.nodes[self.root]
+    fn root(&self) -> Ref<Node> {
+        self.pager.fetch(self.root)
     }
 
-    fn node(&mut self, i: NodeId) -> &mut Node {
-        &mut self.nodes[i]
+    fn node(&self, i: NodeId) -> Ref<Node> {
+        self.pager.fetch(i)
     }
 
     fn suffix(&self, suf: &Suffix) -> &str {
         &self.documents[suf.docid].0[suf.sufid..]
     }
+
+    /// Number of page reads served by the pager so far. Tests reset this with
+    /// `reset_ios` and assert against it to pin down IO behavior.
+    fn ios(&self) -> u64 { self.pager.ios() }
+
+    fn reset_ios(&self) { self.pager.reset_ios() }
 }
 
 #[derive(Debug)]
@@ -150,7 +175,7 @@ impl SufDB {
     fn search<'d, 's, S>(&'d self, needle: S) -> Suffixes<'d, 's>
             where S: IntoCow<'s, str> {
         let needle = needle.into_cow();
-        let cur = self.search_start(&needle).ok();
+        let cur = self.search_lower_bound(&needle);
         Suffixes {
             db: self,
             needle: needle,
@@ -171,24 +196,77 @@ impl SufDB {
     }
 
     fn search_start_from(&self, nid: NodeId, needle: &str) -> SearchResult {
-        let node = &self.nodes[nid];
-        match (node.is_leaf(), self.search_scan(nid, needle)) {
-            (true, Err(kid)) => SearchResult::InsertAt(nid, kid),
-            (true, Ok(kid)) => {
-                if self.suffix(&node.suffixes[kid]).starts_with(needle) {
+        let descend = {
+            let node = self.pager.fetch(nid);
+            if node.is_leaf() {
+                None
+            } else {
+                // Separators are copied up from the minimum of the right
+                // subtree, but a leaf split can leave keys equal to the
+                // separator on its left too, so a needle equal to a separator
+                // descends left (hence `<=`); the lower-bound walk then
+                // follows the leaf chain into the right subtree.
+                let mut kid = node.suffixes.len();
+                for (i, suf) in node.suffixes.iter().enumerate() {
+                    if needle <= self.suffix(suf) {
+                        kid = i;
+                        break
+                    }
+                }
+                Some(node.edges[kid])
+            }
+        };
+        if let Some(child) = descend {
+            return self.search_start_from(child, needle);
+        }
+        match self.search_scan(nid, needle) {
+            Err(kid) => SearchResult::InsertAt(nid, kid),
+            Ok(kid) => {
+                let matched = {
+                    let node = self.pager.fetch(nid);
+                    self.suffix(&node.suffixes[kid]).starts_with(needle)
+                };
+                if matched {
                     SearchResult::Found(nid, kid)
                 } else {
                     SearchResult::InsertAt(nid, kid)
                 }
             }
-            (false, Err(kid)) | (false, Ok(kid)) => {
-                self.search_start_from(node.edges[kid], needle)
+        }
+    }
+
+    /// Position of the first stored suffix that starts with `needle`, if any.
+    ///
+    /// `search_start_from` lands on the leaf where `needle` sorts; when the
+    /// needle is greater than every key in that leaf it falls in the gap
+    /// before the next leaf, so we step along the chain to the true lower
+    /// bound. This overshoot belongs to the read path only — insertion keeps
+    /// the key in the leaf it descended to so the parent separators stay
+    /// valid.
+    fn search_lower_bound(&self, needle: &str) -> Option<(NodeId, KeyId)> {
+        let (mut lnid, mut lkid) = self.search_start(needle).ids();
+        loop {
+            let (len, next) = {
+                let node = self.pager.fetch(lnid);
+                (node.suffixes.len(), node.next)
+            };
+            if lkid < len {
+                break
+            }
+            match next {
+                Some(next) => { lnid = next; lkid = 0; }
+                None => return None,
             }
         }
+        let matched = {
+            let node = self.pager.fetch(lnid);
+            self.suffix(&node.suffixes[lkid]).starts_with(needle)
+        };
+        if matched { Some((lnid, lkid)) } else { None }
     }
 
     fn search_scan(&self, nid: NodeId, needle: &str) -> Result<KeyId, KeyId> {
-        let node = &self.nodes[nid];
+        let node = self.pager.fetch(nid);
         let mut kid: Result<KeyId, KeyId> = Err(node.suffixes.len());
         for (i, suf) in node.suffixes.iter().enumerate() {
             if needle <= self.suffix(suf) {
@@ -200,7 +278,7 @@ impl SufDB {
     }
 
     fn next_suffix(&self, nid: NodeId, kid: KeyId) -> Option<(NodeId, KeyId)> {
-        let node = &self.nodes[nid];
+        let node = self.pager.fetch(nid);
         if kid + 1 >= node.suffixes.len() {
             node.next.map(|nid| (nid, 0))
         } else {
@@ -222,21 +300,601 @@ impl SufDB {
     }
 
     fn insert_document(&mut self, doc: Document) -> DocId {
-        self.documents.push(doc);
-        self.documents.len() - 1
+        match self.free_docs.pop() {
+            Some(docid) => {
+                self.documents[docid] = doc;
+                docid
+            }
+            None => {
+                self.documents.push(doc);
+                self.documents.len() - 1
+            }
+        }
     }
 
     fn insert_suffix(&mut self, suf: Suffix) {
         let (nid, kid) = self.search_insert_at(self.suffix(&suf));
-        self.nodes[nid].suffixes.insert(kid, suf);
-        // if self.nodes[nid].suffixes.len() > self.max_keys() {
-            // let median = self.split(nid);
-        // }
+        self.pager.fetch_dirty(nid).suffixes.insert(kid, suf);
+        let overflow = self.pager.fetch(nid).suffixes.len() > self.max_keys();
+        if overflow {
+            self.split(nid);
+        }
+    }
+
+    /// Split an overfull node in two and push the resulting separator up into
+    /// the parent, allocating a fresh root if `nid` is the root. The split is
+    /// propagated recursively so that every node on the path stays within
+    /// `order` children.
+    fn split(&mut self, nid: NodeId) {
+        let (right, sep, is_leaf, parent) = {
+            let mut node = self.pager.fetch_dirty(nid);
+            let (right, sep) = node.split();
+            (right, sep, node.is_leaf(), node.parent)
+        };
+        let right_id = self.pager.alloc(right);
+        // A leaf keeps the sibling chain threaded: the new right leaf takes
+        // over the old `next` (set in `Node::split`) and the left leaf now
+        // points at it.
+        if is_leaf {
+            self.pager.fetch_dirty(nid).next = Some(right_id);
+        }
+        // Reparent the children that moved into the new right node.
+        let moved = self.pager.fetch(right_id).edges.clone();
+        for child in moved {
+            self.pager.fetch_dirty(child).parent = Some(right_id);
+        }
+        match parent {
+            Some(pid) => {
+                let kid = self.pager.fetch(pid).edges.iter()
+                              .position(|&e| e == nid).unwrap();
+                {
+                    let mut p = self.pager.fetch_dirty(pid);
+                    p.suffixes.insert(kid, sep);
+                    p.edges.insert(kid + 1, right_id);
+                }
+                let overflow =
+                    self.pager.fetch(pid).suffixes.len() > self.max_keys();
+                if overflow {
+                    self.split(pid);
+                }
+            }
+            None => {
+                let new_root = self.pager.alloc(Node {
+                    edges: vec![nid, right_id],
+                    suffixes: vec![sep],
+                    parent: None,
+                    next: None,
+                });
+                self.pager.fetch_dirty(nid).parent = Some(new_root);
+                self.pager.fetch_dirty(right_id).parent = Some(new_root);
+                self.root = new_root;
+            }
+        }
+    }
+
+    /// Remove every suffix belonging to `docid`, rebalancing the tree and
+    /// freeing the document slot so a later `insert` can reclaim the `DocId`.
+    fn remove_document(&mut self, docid: DocId) {
+        if docid >= self.documents.len() || self.free_docs.contains(&docid) {
+            return;
+        }
+        // Recompute the document's suffixes (as insertion did) and drop each.
+        let text = self.documents[docid].0.clone();
+        let (_, table) = SuffixTable::new(&text[..]).into_parts();
+        for sufid in table {
+            self.remove_suffix(&Suffix::new(docid, sufid as usize));
+        }
+        // Separators are copies of leaf keys; a plain removal leaves any
+        // ancestor copy dangling. Repoint those at the surviving minimum of
+        // their subtree before the text goes away so routing stays valid.
+        self.refresh_separators(docid);
+        // Tombstone the document so its id can be reused.
+        self.documents[docid] = Document(String::new());
+        self.free_docs.push(docid);
+    }
+
+    /// Replace every separator still referencing `docid` with the current
+    /// minimum key of the subtree it guards, which no longer mentions `docid`.
+    fn refresh_separators(&mut self, docid: DocId) {
+        for nid in 0..self.pager.len() {
+            if self.pager.is_free(nid) {
+                continue
+            }
+            let seps = {
+                let node = self.pager.fetch(nid);
+                if node.is_leaf() { continue }
+                node.suffixes.clone()
+            };
+            for i in 0..seps.len() {
+                if seps[i].docid == docid {
+                    let child = self.pager.fetch(nid).edges[i + 1];
+                    let min = self.subtree_min(child);
+                    self.pager.fetch_dirty(nid).suffixes[i] = min;
+                }
+            }
+        }
+    }
+
+    /// The smallest key stored under `nid` (leftmost leaf, first suffix).
+    fn subtree_min(&self, mut nid: NodeId) -> Suffix {
+        loop {
+            let next = {
+                let node = self.pager.fetch(nid);
+                if node.is_leaf() {
+                    return node.suffixes[0].clone()
+                }
+                node.edges[0]
+            };
+            nid = next;
+        }
+    }
+
+    fn remove_suffix(&mut self, suf: &Suffix) {
+        let key = self.suffix(suf).to_string();
+        let mut cur = self.search_lower_bound(&key);
+        // Equal keys sort together, so scan the run of keys equal to `key`
+        // for the one with a matching `docid`/`sufid`.
+        while let Some((nid, kid)) = cur {
+            let (equal, target) = {
+                let node = self.pager.fetch(nid);
+                let s = &node.suffixes[kid];
+                (self.suffix(s) == key, *s == *suf)
+            };
+            if !equal {
+                break
+            }
+            if target {
+                self.pager.fetch_dirty(nid).suffixes.remove(kid);
+                self.rebalance(nid);
+                return
+            }
+            cur = self.next_suffix(nid, kid);
+        }
+    }
+
+    /// Restore the minimum-occupancy invariant at `nid` after a removal by
+    /// borrowing from a sibling, or merging with one and recursing into the
+    /// parent; the root collapses when it is left with a single edge.
+    fn rebalance(&mut self, nid: NodeId) {
+        match self.parent_of(nid) {
+            None => {
+                let collapse = {
+                    let root = self.pager.fetch(nid);
+                    !root.is_leaf() && root.suffixes.is_empty()
+                };
+                if collapse {
+                    let child = self.pager.fetch(nid).edges[0];
+                    self.pager.fetch_dirty(child).parent = None;
+                    self.root = child;
+                    self.pager.free(nid);
+                }
+            }
+            Some((pid, ci)) => {
+                if self.pager.fetch(nid).suffixes.len() >= self.min_keys() {
+                    return
+                }
+                let left = if ci > 0 {
+                    Some(self.pager.fetch(pid).edges[ci - 1])
+                } else {
+                    None
+                };
+                let right = {
+                    let p = self.pager.fetch(pid);
+                    if ci + 1 < p.edges.len() { Some(p.edges[ci + 1]) } else { None }
+                };
+                if let Some(l) = left {
+                    if self.pager.fetch(l).suffixes.len() > self.min_keys() {
+                        self.borrow_from_left(pid, ci, l, nid);
+                        return
+                    }
+                }
+                if let Some(r) = right {
+                    if self.pager.fetch(r).suffixes.len() > self.min_keys() {
+                        self.borrow_from_right(pid, ci, nid, r);
+                        return
+                    }
+                }
+                if let Some(l) = left {
+                    self.merge(pid, ci - 1, l, nid);
+                } else if let Some(r) = right {
+                    self.merge(pid, ci, nid, r);
+                }
+                self.rebalance(pid);
+            }
+        }
+    }
+
+    fn parent_of(&self, nid: NodeId) -> Option<(NodeId, KeyId)> {
+        let parent = self.pager.fetch(nid).parent;
+        parent.map(|pid| {
+            let ci = self.pager.fetch(pid).edges.iter()
+                         .position(|&e| e == nid).unwrap();
+            (pid, ci)
+        })
+    }
+
+    /// Rotate a key from the left sibling through the parent into `nid`.
+    fn borrow_from_left(&mut self, pid: NodeId, ci: KeyId,
+                        left: NodeId, nid: NodeId) {
+        if self.pager.fetch(nid).is_leaf() {
+            let moved = self.pager.fetch_dirty(left).suffixes.pop().unwrap();
+            self.pager.fetch_dirty(nid).suffixes.insert(0, moved);
+            let sep = self.pager.fetch(nid).suffixes[0].clone();
+            self.pager.fetch_dirty(pid).suffixes[ci - 1] = sep;
+        } else {
+            let sep = self.pager.fetch(pid).suffixes[ci - 1].clone();
+            self.pager.fetch_dirty(nid).suffixes.insert(0, sep);
+            let (key, edge) = {
+                let mut l = self.pager.fetch_dirty(left);
+                (l.suffixes.pop().unwrap(), l.edges.pop().unwrap())
+            };
+            self.pager.fetch_dirty(pid).suffixes[ci - 1] = key;
+            self.pager.fetch_dirty(nid).edges.insert(0, edge);
+            self.pager.fetch_dirty(edge).parent = Some(nid);
+        }
+    }
+
+    /// Rotate a key from the right sibling through the parent into `nid`.
+    fn borrow_from_right(&mut self, pid: NodeId, ci: KeyId,
+                         nid: NodeId, right: NodeId) {
+        if self.pager.fetch(nid).is_leaf() {
+            let moved = self.pager.fetch_dirty(right).suffixes.remove(0);
+            self.pager.fetch_dirty(nid).suffixes.push(moved);
+            let sep = self.pager.fetch(right).suffixes[0].clone();
+            self.pager.fetch_dirty(pid).suffixes[ci] = sep;
+        } else {
+            let sep = self.pager.fetch(pid).suffixes[ci].clone();
+            self.pager.fetch_dirty(nid).suffixes.push(sep);
+            let (key, edge) = {
+                let mut r = self.pager.fetch_dirty(right);
+                (r.suffixes.remove(0), r.edges.remove(0))
+            };
+            self.pager.fetch_dirty(pid).suffixes[ci] = key;
+            self.pager.fetch_dirty(nid).edges.push(edge);
+            self.pager.fetch_dirty(edge).parent = Some(nid);
+        }
+    }
+
+    /// Merge the `right` node into `left`, pulling down separator `sep_idx`
+    /// from the parent and freeing the emptied `right` slot.
+    fn merge(&mut self, pid: NodeId, sep_idx: KeyId,
+             left: NodeId, right: NodeId) {
+        if self.pager.fetch(left).is_leaf() {
+            let moved = self.pager.fetch(right).suffixes.clone();
+            {
+                let mut l = self.pager.fetch_dirty(left);
+                l.suffixes.extend(moved);
+            }
+            let next = self.pager.fetch(right).next;
+            self.pager.fetch_dirty(left).next = next;
+        } else {
+            let sep = self.pager.fetch(pid).suffixes[sep_idx].clone();
+            let (sufs, edges) = {
+                let r = self.pager.fetch(right);
+                (r.suffixes.clone(), r.edges.clone())
+            };
+            {
+                let mut l = self.pager.fetch_dirty(left);
+                l.suffixes.push(sep);
+                l.suffixes.extend(sufs);
+                l.edges.extend(edges.iter().cloned());
+            }
+            for edge in edges {
+                self.pager.fetch_dirty(edge).parent = Some(left);
+            }
+        }
+        {
+            let mut p = self.pager.fetch_dirty(pid);
+            p.suffixes.remove(sep_idx);
+            p.edges.remove(sep_idx + 1);
+        }
+        self.pager.free(right);
+    }
+}
+
+impl SufDB {
+    /// Find every stored suffix whose prefix is within edit distance
+    /// `max_edits` of `needle`, returning each match paired with the edit
+    /// distance it achieved so callers can rank by it.
+    ///
+    /// A Levenshtein automaton is driven over the tree: internal subtrees are
+    /// pruned when the common prefix of their bounding separators already
+    /// kills the automaton, and at a leaf the automaton is stepped over each
+    /// candidate suffix a `char` at a time (consistent with the `char`-based
+    /// exact matching).
+    fn search_fuzzy<'s, S>(&self, needle: S, max_edits: usize)
+            -> Vec<(Suffix, usize)> where S: IntoCow<'s, str> {
+        let needle = needle.into_cow();
+        let mut out = vec![];
+        if needle.is_empty() {
+            return out;
+        }
+        let lev = Levenshtein::new(&needle, max_edits);
+        self.fuzzy_descend(self.root, &lev, &mut out);
+        out
+    }
+
+    fn fuzzy_descend(&self, nid: NodeId, lev: &Levenshtein,
+                     out: &mut Vec<(Suffix, usize)>) {
+        enum Kind { Leaf(Vec<Suffix>), Internal(Vec<NodeId>, Vec<Suffix>) }
+        let kind = {
+            let node = self.pager.fetch(nid);
+            if node.is_leaf() {
+                Kind::Leaf(node.suffixes.clone())
+            } else {
+                Kind::Internal(node.edges.clone(), node.suffixes.clone())
+            }
+        };
+        match kind {
+            Kind::Leaf(sufs) => {
+                for suf in &sufs {
+                    if let Some(d) = self.fuzzy_match(lev, suf) {
+                        out.push((suf.clone(), d));
+                    }
+                }
+            }
+            Kind::Internal(edges, seps) => {
+                for i in 0..edges.len() {
+                    // All keys in edge `i` live between separators `seps[i-1]`
+                    // and `seps[i]`, so they share that pair's common prefix;
+                    // if the automaton is already dead after consuming it the
+                    // whole subtree can be skipped.
+                    let alive = match (i.checked_sub(1).map(|j| &seps[j]),
+                                       seps.get(i)) {
+                        (Some(l), Some(r)) =>
+                            self.fuzzy_alive_along_lcp(lev,
+                                                       self.suffix(l),
+                                                       self.suffix(r)),
+                        _ => true,
+                    };
+                    if alive {
+                        self.fuzzy_descend(edges[i], lev, out);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Step the automaton over a single suffix, returning the smallest edit
+    /// distance at which any prefix of the suffix matches `needle`.
+    fn fuzzy_match(&self, lev: &Levenshtein, suf: &Suffix) -> Option<usize> {
+        let mut st = lev.start();
+        let mut best = lev.accept(&st);
+        for c in self.suffix(suf).chars() {
+            st = lev.step(&st, c);
+            if let Some(d) = lev.accept(&st) {
+                best = Some(match best {
+                    Some(b) => ::std::cmp::min(b, d),
+                    None => d,
+                });
+            }
+            // The minimum of the DP row only ever grows, so once it exceeds
+            // `k` no longer suffix can revive the match.
+            if lev.dead(&st) {
+                break
+            }
+        }
+        best
+    }
+
+    fn fuzzy_alive_along_lcp(&self, lev: &Levenshtein, a: &str, b: &str) -> bool {
+        let mut st = lev.start();
+        if lev.accept(&st).is_some() {
+            return true
+        }
+        for (x, y) in a.chars().zip(b.chars()) {
+            if x != y {
+                break
+            }
+            st = lev.step(&st, x);
+            // A match on this shared prefix holds for every key in the
+            // subtree, so keep it; only prune once the automaton dies with no
+            // match yet seen.
+            if lev.accept(&st).is_some() {
+                return true
+            }
+            if lev.dead(&st) {
+                return false
+            }
+        }
+        true
+    }
+}
+
+/// An incremental Levenshtein automaton over a `needle`, matched against a
+/// prefix of some text. The state is the full (unbanded) DP row, holding every
+/// needle position whatever its cost, where `row[i]` is the
+/// fewest edits turning the first `i` chars of the needle into the text
+/// consumed so far.
+struct Levenshtein {
+    pattern: Vec<char>,
+    k: usize,
+}
+
+#[derive(Clone)]
+struct LevState {
+    row: Vec<usize>,
+}
+
+impl Levenshtein {
+    fn new(needle: &str, k: usize) -> Levenshtein {
+        Levenshtein { pattern: needle.chars().collect(), k: k }
+    }
+
+    fn start(&self) -> LevState {
+        LevState { row: (0..self.pattern.len() + 1).collect() }
+    }
+
+    /// Advance the automaton by one text `char`.
+    fn step(&self, st: &LevState, c: char) -> LevState {
+        let m = self.pattern.len();
+        let mut row = vec![0; m + 1];
+        row[0] = st.row[0] + 1;
+        for i in 1..m + 1 {
+            let sub = st.row[i - 1]
+                + if self.pattern[i - 1] == c { 0 } else { 1 };
+            let del = st.row[i] + 1;   // an extra char in the text
+            let ins = row[i - 1] + 1;  // a skipped char in the needle
+            row[i] = ::std::cmp::min(sub, ::std::cmp::min(del, ins));
+        }
+        LevState { row: row }
+    }
+
+    /// Edit distance to match the whole needle against the consumed text, if
+    /// it is within `k`.
+    fn accept(&self, st: &LevState) -> Option<usize> {
+        let d = st.row[self.pattern.len()];
+        if d <= self.k { Some(d) } else { None }
+    }
+
+    /// A state is dead once the cheapest live alignment already exceeds `k`.
+    fn dead(&self, st: &LevState) -> bool {
+        st.row.iter().cloned().min().unwrap() > self.k
+    }
+}
+
+/// A boolean query over the database. A `Term` matches the documents that
+/// contain a given substring; `And`/`Or` combine their children by
+/// intersecting / unioning the matched document sets.
+#[derive(Clone, Debug)]
+enum Operation {
+    And(Vec<Operation>),
+    Or(Vec<Operation>),
+    Term(String),
+}
+
+impl Operation {
+    /// Parse a flat query like `"ana AND ple"` or `"a OR b AND c"`. `OR` binds
+    /// looser than `AND`; everything else is a term (whitespace-joined).
+    fn parse(query: &str) -> Operation {
+        let tokens: Vec<String> =
+            query.split_whitespace().map(|s| s.to_string()).collect();
+        Operation::parse_or(&tokens)
     }
 
-    // fn split(&mut self, nid: NodeId) -> Suffix {
-        // let new = self.nodes[nid].split();
-    // }
+    fn parse_or(tokens: &[String]) -> Operation {
+        let groups = Operation::split_on(tokens, "OR");
+        match groups.len() {
+            0 => Operation::Term(String::new()),
+            1 => Operation::parse_and(&groups[0]),
+            _ => Operation::Or(groups.iter()
+                                     .map(|g| Operation::parse_and(g))
+                                     .collect()),
+        }
+    }
+
+    fn parse_and(tokens: &[String]) -> Operation {
+        let groups = Operation::split_on(tokens, "AND");
+        match groups.len() {
+            0 => Operation::Term(String::new()),
+            1 => Operation::Term(groups[0].join(" ")),
+            _ => Operation::And(groups.iter()
+                                      .map(|g| Operation::Term(g.join(" ")))
+                                      .collect()),
+        }
+    }
+
+    /// Split token runs on an operator, dropping empty runs so a leading,
+    /// trailing, or doubled operator does not introduce an empty term.
+    fn split_on(tokens: &[String], sep: &str) -> Vec<Vec<String>> {
+        let mut out = vec![];
+        let mut cur = vec![];
+        for t in tokens {
+            if t == sep {
+                if !cur.is_empty() {
+                    out.push(cur);
+                }
+                cur = vec![];
+            } else {
+                cur.push(t.clone());
+            }
+        }
+        if !cur.is_empty() {
+            out.push(cur);
+        }
+        out
+    }
+}
+
+impl SufDB {
+    /// Evaluate a boolean query, yielding the matching document ids in
+    /// ascending order.
+    fn search_query(&self, op: &Operation) -> ::std::vec::IntoIter<DocId> {
+        let mut docs: Vec<DocId> = self.eval_query(op).into_iter().collect();
+        docs.sort();
+        docs.into_iter()
+    }
+
+    fn eval_query(&self, op: &Operation) -> HashSet<DocId> {
+        match *op {
+            Operation::Term(ref term) =>
+                self.search(&term[..]).map(|s| s.docid).collect(),
+            Operation::And(ref ops) => {
+                let mut ops = ops.iter();
+                match ops.next() {
+                    None => HashSet::new(),
+                    Some(first) => {
+                        let mut acc = self.eval_query(first);
+                        for op in ops {
+                            if acc.is_empty() {
+                                break
+                            }
+                            let rhs = self.eval_query(op);
+                            acc = acc.intersection(&rhs).cloned().collect();
+                        }
+                        acc
+                    }
+                }
+            }
+            Operation::Or(ref ops) => {
+                let mut acc = HashSet::new();
+                for op in ops {
+                    acc.extend(self.eval_query(op));
+                }
+                acc
+            }
+        }
+    }
+}
+
+impl SufDB {
+    /// Every distinct stored key that is a *prefix* of `needle`, i.e. every
+    /// key `s` with `needle.starts_with(s)`, shortest first. This is the dual
+    /// of `search`, which finds keys that start *with* the needle.
+    fn find_prefixes<'s, S>(&self, needle: S) -> Vec<String>
+            where S: IntoCow<'s, str> {
+        let needle = needle.into_cow();
+        let mut out = vec![];
+        // Each prefix of `needle` ending on a char boundary is a candidate
+        // key; descend for it and keep it when it is stored verbatim.
+        let bounds = needle.char_indices().map(|(i, _)| i).skip(1)
+                           .chain(Some(needle.len()));
+        for b in bounds {
+            let prefix = &needle[..b];
+            if self.is_key(prefix) {
+                out.push(prefix.to_string());
+            }
+        }
+        out
+    }
+
+    /// The longest stored key that is a prefix of `needle`, if any.
+    fn find_longest_prefix<'s, S>(&self, needle: S) -> Option<String>
+            where S: IntoCow<'s, str> {
+        self.find_prefixes(needle).pop()
+    }
+
+    /// Whether `key` is stored verbatim. The smallest stored suffix that has
+    /// `key` as a prefix equals `key` exactly when `key` is itself present.
+    fn is_key(&self, key: &str) -> bool {
+        match self.search_lower_bound(key) {
+            Some((nid, kid)) => {
+                let node = self.pager.fetch(nid);
+                self.suffix(&node.suffixes[kid]) == key
+            }
+            None => false,
+        }
+    }
 }
 
 impl Node {
@@ -253,21 +911,236 @@ impl Node {
         self.edges.is_empty()
     }
 
-    fn split(&mut self) -> Node {
+    /// Split this node down the middle, returning the freshly allocated right
+    /// half together with the separator key that belongs in the parent.
+    ///
+    /// A leaf *copies up* its first right-hand key (so the key still lives in
+    /// the leaf that owns the data), while an internal node *moves up* its
+    /// median key. Internal edges split at `median + 1` so that the median
+    /// key's left/right subtrees end up on the correct sides.
+    fn split(&mut self) -> (Node, Suffix) {
         let median = self.suffixes.len() / 2;
-        let split_sufs = self.suffixes.split_off(median);
-        let split_edges = if self.is_leaf() {
-            vec![]
+        if self.is_leaf() {
+            let right_sufs = self.suffixes.split_off(median);
+            let sep = right_sufs[0].clone();
+            let right = Node {
+                edges: vec![],
+                suffixes: right_sufs,
+                parent: self.parent,
+                next: self.next,
+            };
+            (right, sep)
         } else {
-            self.edges.split_off(median)
+            let right_edges = self.edges.split_off(median + 1);
+            let mut right_sufs = self.suffixes.split_off(median);
+            let sep = right_sufs.remove(0);
+            let right = Node {
+                edges: right_edges,
+                suffixes: right_sufs,
+                parent: self.parent,
+                next: None,
+            };
+            (right, sep)
+        }
+    }
+}
+
+/// Default number of nodes the buffer cache keeps resident.
+const DEFAULT_CACHE_CAP: usize = 1024;
+
+/// A page-backed node store fronted by a bounded LRU buffer cache.
+///
+/// Each `Node` occupies its own page in `pages` (the stand-in for an on-disk
+/// page file); the cache keeps recently touched pages resident and
+/// deserialized and only decodes from `pages` on a miss, modelled after
+/// MeiliSearch's `DatabaseCache`. Dirty pages are encoded back into `pages`
+/// when they are evicted. `ios` counts page reads so callers can reason about
+/// the number of disk IOs a `search` or `insert` performs.
+struct Pager {
+    /// Serialized backing store, one page per `NodeId`.
+    pages: RefCell<Vec<Vec<u8>>>,
+    /// Resident, deserialized pages plus recency order.
+    cache: RefCell<Cache>,
+    /// Maximum number of resident pages.
+    cap: usize,
+    /// Count of pages decoded from the backing store (cache misses).
+    ios: Cell<u64>,
+    /// Pages freed by deletion, available for reuse.
+    free: Vec<NodeId>,
+}
+
+struct Cache {
+    resident: HashMap<NodeId, Slot>,
+    /// Least-recently-used order; the front is the next eviction victim.
+    lru: Vec<NodeId>,
+}
+
+struct Slot {
+    node: Node,
+    dirty: bool,
+}
+
+impl Pager {
+    fn new(cap: usize) -> Pager {
+        assert!(cap > 0);
+        Pager {
+            pages: RefCell::new(vec![]),
+            cache: RefCell::new(Cache { resident: HashMap::new(), lru: vec![] }),
+            cap: cap,
+            ios: Cell::new(0),
+            free: vec![],
+        }
+    }
+
+    /// Total number of page slots ever allocated, including freed ones (see
+    /// `is_free`) and any still resident only in the cache.
+    fn len(&self) -> usize { self.pages.borrow().len() }
+
+    fn ios(&self) -> u64 { self.ios.get() }
+
+    fn is_free(&self, nid: NodeId) -> bool { self.free.contains(&nid) }
+
+    fn reset_ios(&self) { self.ios.set(0); }
+
+    /// Allocate a page for `node`, reusing a freed slot when one is available.
+    /// The node starts life resident and dirty.
+    fn alloc(&mut self, node: Node) -> NodeId {
+        let nid = match self.free.pop() {
+            Some(id) => id,
+            None => {
+                self.pages.borrow_mut().push(vec![]);
+                self.pages.borrow().len() - 1
+            }
         };
-        Node {
-            edges: split_edges,
-            suffixes: split_sufs,
-            parent: self.parent,
-            next: None,
+        self.evict_if_needed();
+        let mut c = self.cache.borrow_mut();
+        c.resident.insert(nid, Slot { node: node, dirty: true });
+        c.lru.push(nid);
+        nid
+    }
+
+    /// Mark a page as free and drop it from the cache so its slot can be
+    /// reused by a later `alloc`.
+    fn free(&mut self, nid: NodeId) {
+        {
+            let mut c = self.cache.borrow_mut();
+            c.resident.remove(&nid);
+            if let Some(pos) = c.lru.iter().position(|&x| x == nid) {
+                c.lru.remove(pos);
+            }
         }
+        self.pages.borrow_mut()[nid].clear();
+        self.free.push(nid);
+    }
+
+    fn fetch(&self, nid: NodeId) -> Ref<Node> {
+        self.ensure_resident(nid);
+        Ref::map(self.cache.borrow(), |c| &c.resident[&nid].node)
+    }
+
+    fn fetch_dirty(&self, nid: NodeId) -> RefMut<Node> {
+        self.ensure_resident(nid);
+        RefMut::map(self.cache.borrow_mut(), |c| {
+            let slot = c.resident.get_mut(&nid).unwrap();
+            slot.dirty = true;
+            &mut slot.node
+        })
     }
+
+    /// Load `nid` into the cache if it is not already resident, evicting the
+    /// least-recently-used page first when the cache is full.
+    fn ensure_resident(&self, nid: NodeId) {
+        if self.cache.borrow().resident.contains_key(&nid) {
+            self.touch(nid);
+            return;
+        }
+        self.evict_if_needed();
+        let node = {
+            let pages = self.pages.borrow();
+            decode_node(&pages[nid])
+        };
+        self.ios.set(self.ios.get() + 1);
+        let mut c = self.cache.borrow_mut();
+        c.resident.insert(nid, Slot { node: node, dirty: false });
+        c.lru.push(nid);
+    }
+
+    fn touch(&self, nid: NodeId) {
+        let mut c = self.cache.borrow_mut();
+        if let Some(pos) = c.lru.iter().position(|&x| x == nid) {
+            c.lru.remove(pos);
+            c.lru.push(nid);
+        }
+    }
+
+    fn evict_if_needed(&self) {
+        let victim = {
+            let c = self.cache.borrow();
+            if c.resident.len() < self.cap {
+                return;
+            }
+            c.lru.first().cloned()
+        };
+        if let Some(v) = victim {
+            let slot = {
+                let mut c = self.cache.borrow_mut();
+                let pos = c.lru.iter().position(|&x| x == v).unwrap();
+                c.lru.remove(pos);
+                c.resident.remove(&v).unwrap()
+            };
+            if slot.dirty {
+                let bytes = encode_node(&slot.node);
+                self.pages.borrow_mut()[v] = bytes;
+            }
+        }
+    }
+}
+
+/// Serialize a `Node` into a page: the `edges` and `suffixes` vectors followed
+/// by the `parent` and `next` pointers, each integer little-endian and each
+/// optional pointer using an all-ones sentinel for `None`.
+fn encode_node(node: &Node) -> Vec<u8> {
+    fn put(buf: &mut Vec<u8>, v: u64) {
+        for i in 0..8 { buf.push((v >> (i * 8)) as u8); }
+    }
+    fn opt(o: Option<NodeId>) -> u64 {
+        match o { Some(x) => x as u64, None => !0 }
+    }
+    let mut buf = vec![];
+    put(&mut buf, node.edges.len() as u64);
+    for &e in &node.edges { put(&mut buf, e as u64); }
+    put(&mut buf, node.suffixes.len() as u64);
+    for s in &node.suffixes {
+        put(&mut buf, s.docid as u64);
+        put(&mut buf, s.sufid as u64);
+    }
+    put(&mut buf, opt(node.parent));
+    put(&mut buf, opt(node.next));
+    buf
+}
+
+fn decode_node(buf: &[u8]) -> Node {
+    fn get(buf: &[u8], off: &mut usize) -> u64 {
+        let mut v = 0u64;
+        for i in 0..8 { v |= (buf[*off + i] as u64) << (i * 8); }
+        *off += 8;
+        v
+    }
+    fn opt(v: u64) -> Option<NodeId> {
+        if v == !0 { None } else { Some(v as NodeId) }
+    }
+    let mut off = 0;
+    let nedges = get(buf, &mut off) as usize;
+    let edges = (0..nedges).map(|_| get(buf, &mut off) as NodeId).collect();
+    let nsufs = get(buf, &mut off) as usize;
+    let suffixes = (0..nsufs).map(|_| {
+        let docid = get(buf, &mut off) as DocId;
+        let sufid = get(buf, &mut off) as SuffixId;
+        Suffix::new(docid, sufid)
+    }).collect();
+    let parent = opt(get(buf, &mut off));
+    let next = opt(get(buf, &mut off));
+    Node { edges: edges, suffixes: suffixes, parent: parent, next: next }
 }
 
 impl<'a, S> iter::FromIterator<S> for SufDB where S: IntoCow<'a, str> {
@@ -299,17 +1172,19 @@ impl fmt::Debug for SufDB {
         div!();
         w!("order: {}", self.order);
         w!("#documents: {}", self.documents.len());
-        w!("#nodes: {}", self.nodes.len());
+        w!("#nodes: {}", self.pager.len());
         div!(39);
         for doc in &self.documents { w!("{:?}", doc); }
         div!(39);
-        for (i, n) in self.nodes.iter().enumerate() {
+        for i in 0..self.pager.len() {
+            if self.pager.is_free(i) { continue; }
             if i > 0 { div!(15); }
             if self.root == i {
                 w!("id: {} (root)", i);
             } else {
                 w!("id: {}", i);
             };
+            let n = self.pager.fetch(i);
             w!("parent: {:?}", n.parent);
             w!("next: {:?}", n.next);
             w!("edges: {:?}", n.edges);
@@ -335,17 +1210,23 @@ struct Suffixes<'d, 's> {
 }
 
 impl<'d, 's> Iterator for Suffixes<'d, 's> {
-    type Item = &'d Suffix;
+    type Item = Suffix;
 
-    fn next(&mut self) -> Option<&'d Suffix> {
+    fn next(&mut self) -> Option<Suffix> {
         if self.needle.is_empty() {
             return None;
         }
         if let Some((nid, kid)) = self.cur {
-            let suf = &self.db.nodes[nid].suffixes[kid];
-            if self.db.suffix(suf).starts_with(&self.needle) {
+            let (suf, matched) = {
+                let node = self.db.pager.fetch(nid);
+                let suf = node.suffixes[kid].clone();
+                let matched = self.db.suffix(&node.suffixes[kid])
+                                  .starts_with(&self.needle);
+                (suf, matched)
+            };
+            if matched {
                 self.cur = self.db.next_suffix(nid, kid);
-                Some(&self.db.nodes[nid].suffixes[kid])
+                Some(suf)
             } else {
                 None
             }
@@ -361,7 +1242,7 @@ mod tests {
     use std::fmt::Debug;
     use std::hash::Hash;
     use std::iter::{FromIterator, IntoIterator};
-    use super::{SufDB, Suffix, DocId, SuffixId};
+    use super::{SufDB, Suffix, DocId, SuffixId, Operation};
 
     fn createdb<'a, I>(docs: I) -> SufDB
             where I: IntoIterator,
@@ -432,6 +1313,116 @@ mod tests {
         assert_search(&db, "☃", vec![(0, 0), (0, 6)]);
     }
 
+    #[test]
+    fn search_forces_splits() {
+        // A small order makes the root overflow quickly, exercising leaf and
+        // internal splits and the separator copy-/move-up.
+        let mut db = SufDB::with_order(4);
+        db.insert("banana");
+        db.insert("apple");
+        db.insert("orange");
+        assert!(db.root != 0 || db.pager.len() > 1);
+        assert!(db.contains("ana"));
+        assert!(db.contains("ple"));
+        assert!(db.contains("ange"));
+        assert!(db.contains("banana"));
+        assert!(!db.contains("z"));
+        assert_search(&db, "an", vec![(0, 1), (0, 3), (2, 2)]);
+    }
+
+    #[test]
+    fn page_reads_are_counted() {
+        // A cache smaller than the tree forces the pager to read pages back
+        // from the backing store on lookup rather than serving every node
+        // from memory.
+        let mut db = SufDB::with_order_and_cache(4, 2);
+        for w in &["banana", "apple", "orange", "grape", "melon"] {
+            db.insert(*w);
+        }
+        db.reset_ios();
+        assert!(db.contains("an"));
+        assert!(db.ios() > 0);
+    }
+
+    #[test]
+    fn fuzzy_exact_is_zero() {
+        let db = createdb(vec!["apple"]);
+        let got = db.search_fuzzy("app", 1);
+        assert!(got.iter().any(|&(ref s, d)| *s == suf(0, 0) && d == 0));
+    }
+
+    #[test]
+    fn fuzzy_one_edit() {
+        let db = createdb(vec!["banana", "apple"]);
+        // "banena" is one substitution away from the "banana" suffix.
+        let got = db.search_fuzzy("banena", 1);
+        assert!(got.iter().any(|&(ref s, d)| *s == suf(0, 0) && d == 1));
+        // ... and nothing matches within zero edits.
+        assert!(db.search_fuzzy("banena", 0).is_empty());
+    }
+
+    #[test]
+    fn query_and_disjoint() {
+        let db = createdb(vec!["banana", "apple"]);
+        let op = Operation::parse("ana AND ple");
+        let got: Vec<DocId> = db.search_query(&op).collect();
+        // "ana" is only in banana, "ple" only in apple: no document has both.
+        assert!(got.is_empty());
+    }
+
+    #[test]
+    fn query_and_or() {
+        let db = createdb(vec!["banana", "apple", "pineapple"]);
+        let op = Operation::parse("ple AND app");
+        let got: Vec<DocId> = db.search_query(&op).collect();
+        assert_eq!(got, vec![1, 2]);
+
+        let op = Operation::parse("ana OR app");
+        let got: Vec<DocId> = db.search_query(&op).collect();
+        assert_eq!(got, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn prefixes() {
+        let db = createdb(vec!["a", "ap", "app", "apple", "banana"]);
+        let mut got = db.find_prefixes("apple");
+        got.sort();
+        assert_eq!(got, vec!["a".to_string(), "ap".to_string(),
+                             "app".to_string(), "apple".to_string()]);
+        assert_eq!(db.find_longest_prefix("apply"), Some("app".to_string()));
+        assert_eq!(db.find_longest_prefix("apple"), Some("apple".to_string()));
+        assert_eq!(db.find_longest_prefix("xyz"), None);
+    }
+
+    #[test]
+    fn remove_document_basic() {
+        let mut db = SufDB::with_order(4);
+        db.insert("banana");
+        db.insert("apple");
+        assert!(db.contains("ana"));
+        assert!(db.contains("ple"));
+        db.remove_document(0);
+        // banana's suffixes are gone; apple's remain.
+        assert!(!db.contains("ana"));
+        assert!(!db.contains("banana"));
+        assert!(db.contains("ple"));
+        assert!(db.contains("apple"));
+    }
+
+    #[test]
+    fn remove_reclaims_docid() {
+        let mut db = SufDB::with_order(4);
+        db.insert("banana");
+        db.insert("apple");
+        db.remove_document(0);
+        db.insert("cherry");
+        assert!(db.contains("err"));
+        assert!(db.contains("apple"));
+        assert!(!db.contains("ana"));
+        // The tombstoned slot was reused rather than grown.
+        assert_eq!(db.documents.len(), 2);
+    }
+
     #[test]
     fn scratch() {
         let mut db = SufDB::new();